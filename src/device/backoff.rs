@@ -0,0 +1,48 @@
+// Implements an exponential backoff policy for reconnect attempts: the
+// delay starts small and doubles on each consecutive failure, up to a
+// ceiling, and resets back to the initial delay once the device
+// delivers data again. This keeps a device that's down for a while
+// from hammering its endpoint (and Redis, via the service-state
+// reports) at a fixed cadence.
+
+use std::time::Duration;
+
+pub struct Backoff {
+    initial: Duration,
+    ceiling: Duration,
+    attempts: u32,
+    current: Duration
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, ceiling: Duration) -> Backoff {
+	Backoff { initial, ceiling, attempts: 0, current: initial }
+    }
+
+    // The number of consecutive failures seen since the last reset.
+
+    pub fn attempts(&self) -> u32 {
+	self.attempts
+    }
+
+    // Returns the delay to wait before the next attempt and advances
+    // the policy -- doubling the delay, up to the ceiling -- for next
+    // time.
+
+    pub fn next_delay(&mut self) -> Duration {
+	let delay = self.current;
+
+	self.attempts += 1;
+	self.current = std::cmp::min(self.current * 2, self.ceiling);
+	delay
+    }
+
+    // Called once the device delivers data again (the sump pump calls
+    // it on each successfully read frame); starts the policy back at
+    // its initial delay.
+
+    pub fn reset(&mut self) {
+	self.attempts = 0;
+	self.current = self.initial;
+    }
+}
@@ -40,33 +40,50 @@ pub enum Type {
     Bool(bool),
     Int(i64),
     Flt(f64),
-    Str(String)
+    Str(String),
+    Array(Vec<Type>)
 }
 
 impl Type {
+    // Returns the element at index `i` of an `Array`, or an explicit,
+    // descriptive error -- rather than panicking -- if `self` isn't an
+    // array or if the index is out of range.
+
+    pub fn index(&self, i: usize) -> RedisResult<&Type> {
+	match self {
+	    Type::Array(items) => items.get(i).ok_or_else(|| {
+		RedisError::from((ErrorKind::TypeError,
+				  "index out of range",
+				  format!("index {} is out of range for array of length {}",
+					  i, items.len())))
+	    }),
+	    _ => Err(RedisError::from((ErrorKind::TypeError, "not an array")))
+	}
+    }
+
     #[doc(hidden)]
-    fn decode_integer(buf: &[u8]) -> RedisResult<Self> {
+    fn decode_integer(buf: &[u8]) -> RedisResult<(Self, usize)> {
 	if buf.len() >= 8 {
 	    let buf = buf[..8].try_into().unwrap();
 
-	    return Ok(Type::Int(i64::from_be_bytes(buf)))
+	    return Ok((Type::Int(i64::from_be_bytes(buf)), 8))
 	}
 	Err(RedisError::from((ErrorKind::TypeError, "integer data too short")))
     }
 
     #[doc(hidden)]
-    fn decode_float(buf: &[u8]) -> RedisResult<Self> {
+    fn decode_float(buf: &[u8]) -> RedisResult<(Self, usize)> {
 	if buf.len() >= 8 {
 	    let buf = buf[..8].try_into().unwrap();
 
-	    return Ok(Type::Flt(f64::from_be_bytes(buf)))
+	    return Ok((Type::Flt(f64::from_be_bytes(buf)), 8))
 	}
 	Err(RedisError::from((ErrorKind::TypeError,
 			      "floating point data too short")))
     }
 
     #[doc(hidden)]
-    fn decode_string(buf: &[u8]) -> RedisResult<Self> {
+    fn decode_string(buf: &[u8]) -> RedisResult<(Self, usize)> {
 	if buf.len() >= 4 {
 	    let len_buf = buf[..4].try_into().unwrap();
 	    let len = u32::from_be_bytes(len_buf) as usize;
@@ -75,7 +92,7 @@ impl Type {
 		let str_vec = buf[4..4 + len].to_vec();
 
 		return match String::from_utf8(str_vec) {
-		    Ok(s) => Ok(Type::Str(s)),
+		    Ok(s) => Ok((Type::Str(s), 4 + len)),
 		    Err(_) => Err(RedisError::from((ErrorKind::TypeError,
 						    "string not UTF-8")))
 		}
@@ -83,26 +100,91 @@ impl Type {
 	}
 	Err(RedisError::from((ErrorKind::TypeError, "string data too short")))
     }
-}
 
-// Implement the `ToRedisArgs` trait. This allows us to specify a
-// `Type` when writing values to redis so they get encoded correctly.
+    // Decodes the `u32` element count and then each element, in order,
+    // from its own tagged encoding. Returns a `TypeError` as soon as the
+    // buffer runs short, whether that's the count itself or partway
+    // through an element.
 
-impl ToRedisArgs for Type {
-    fn write_redis_args<W>(&self, out: &mut W)
-    where W: ?Sized + RedisWrite,
-    {
+    #[doc(hidden)]
+    fn decode_array(buf: &[u8]) -> RedisResult<(Self, usize)> {
+	if buf.len() >= 4 {
+	    let len_buf = buf[..4].try_into().unwrap();
+	    let count = u32::from_be_bytes(len_buf) as usize;
+
+	    // Each element needs at least one byte (its tag), so a count
+	    // that can't possibly fit in the rest of `buf` is bogus --
+	    // reject it before `with_capacity` turns it into a
+	    // multi-gigabyte allocation.
+
+	    if count > buf.len() - 4 {
+		return Err(RedisError::from((ErrorKind::TypeError,
+					     "array data too short")))
+	    }
+
+	    let mut items = Vec::with_capacity(count);
+	    let mut consumed = 4;
+
+	    for _ in 0..count {
+		if consumed > buf.len() {
+		    return Err(RedisError::from((ErrorKind::TypeError,
+						 "array data too short")))
+		}
+
+		let (item, item_len) = Self::decode_tagged(&buf[consumed..])?;
+
+		items.push(item);
+		consumed += item_len;
+	    }
+	    return Ok((Type::Array(items), consumed))
+	}
+	Err(RedisError::from((ErrorKind::TypeError, "array data too short")))
+    }
+
+    // Decodes one tag-prefixed value from the front of `buf`, returning
+    // the value and the total number of bytes it consumed (including
+    // the tag byte). This is the recursive step `decode_array` uses for
+    // its elements, and is also what `FromRedisValue` dispatches to at
+    // the top level.
+
+    #[doc(hidden)]
+    fn decode_tagged(buf: &[u8]) -> RedisResult<(Self, usize)> {
+	if buf.is_empty() {
+	    return Err(RedisError::from((ErrorKind::TypeError, "value data too short")))
+	}
+
+	let (value, consumed) = match buf[0] as char {
+	    'F' => (Type::Bool(false), 0),
+	    'T' => (Type::Bool(true), 0),
+	    'I' => Self::decode_integer(&buf[1..])?,
+	    'D' => Self::decode_float(&buf[1..])?,
+	    'S' => Self::decode_string(&buf[1..])?,
+	    'A' => Self::decode_array(&buf[1..])?,
+
+	    _ => return Err(RedisError::from((ErrorKind::TypeError, "unknown tag")))
+	};
+
+	Ok((value, 1 + consumed))
+    }
+
+    // Encodes `self` into its tagged byte representation. This is used
+    // both to write a top-level `Type` as a single Redis arg and,
+    // recursively, to embed each element of an `Array` in its parent's
+    // buffer.
+
+    #[doc(hidden)]
+    fn encode(&self) -> Vec<u8> {
 	match self {
-	    Type::Nil => out.write_arg(b""),
-	    Type::Bool(false) => out.write_arg(b"F"),
-	    Type::Bool(true) => out.write_arg(b"T"),
+	    Type::Nil => Vec::new(),
+	    Type::Bool(false) => vec!['F' as u8],
+	    Type::Bool(true) => vec!['T' as u8],
 
 	    Type::Int(v) => {
 		let mut buf: Vec<u8> = Vec::with_capacity(9);
 
 		buf.push('I' as u8);
 		buf.extend_from_slice(&v.to_be_bytes());
-		out.write_arg(&buf)
+		buf
 	    },
 
 	    Type::Flt(v) => {
@@ -110,7 +192,7 @@ impl ToRedisArgs for Type {
 
 		buf.push('D' as u8);
 		buf.extend_from_slice(&v.to_be_bytes());
-		out.write_arg(&buf)
+		buf
 	    },
 
 	    Type::Str(s) => {
@@ -120,12 +202,34 @@ impl ToRedisArgs for Type {
 		buf.push('S' as u8);
 		buf.extend_from_slice(&(s.len() as u32).to_be_bytes());
 		buf.extend_from_slice(&s);
-		out.write_arg(&buf)
+		buf
+	    },
+
+	    Type::Array(items) => {
+		let mut buf: Vec<u8> = Vec::new();
+
+		buf.push('A' as u8);
+		buf.extend_from_slice(&(items.len() as u32).to_be_bytes());
+		for item in items {
+		    buf.extend_from_slice(&item.encode());
+		}
+		buf
 	    }
 	}
     }
 }
 
+// Implement the `ToRedisArgs` trait. This allows us to specify a
+// `Type` when writing values to redis so they get encoded correctly.
+
+impl ToRedisArgs for Type {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where W: ?Sized + RedisWrite,
+    {
+	out.write_arg(&self.encode())
+    }
+}
+
 // Implement the `FromRedisValue` trait. This trait tries to decode a
 // `Type` from a string stored in redis.
 
@@ -138,20 +242,7 @@ impl FromRedisValue for Type {
 	    // to be decoded.
 
 	    if buf.len() > 0 {
-		match buf[0] as char {
-		    'F' => Ok(Type::Bool(false)),
-		    'T' => Ok(Type::Bool(true)),
-		    'I' => Self::decode_integer(&buf[1..]),
-		    'D' => Self::decode_float(&buf[1..]),
-		    'S' => Self::decode_string(&buf[1..]),
-
-		    // Any other character in the tag field is unknown
-		    // and can't be decoded as a `Type`.
-
-		    _ =>
-			Err(RedisError::from((ErrorKind::TypeError,
-					      "unknown tag")))
-		}
+		Self::decode_tagged(buf).map(|(value, _)| value)
 	    } else {
 		Ok(Type::Nil)
 	    }
@@ -308,4 +399,61 @@ mod tests {
 	    assert_eq!(*rv, Type::Int(*v).to_redis_args());
 	}
     }
+
+    // Round-trips a nested array -- an array of arrays containing a
+    // mix of scalar types -- through the encoder and back.
+
+    #[tokio::test]
+    async fn test_nested_array_round_trip() {
+	let value = Type::Array(vec![
+	    Type::Array(vec![Type::Int(1), Type::Int(2), Type::Int(3)]),
+	    Type::Str("rgb".to_string()),
+	    Type::Bool(true),
+	    Type::Array(vec![])
+	]);
+
+	let args = value.to_redis_args();
+
+	assert_eq!(1, args.len());
+
+	let data = Value::Data(args[0].clone());
+
+	assert_eq!(Ok(value), from_redis_value(&data));
+    }
+
+    // A truncated array -- claiming more elements than the buffer
+    // actually holds -- must report a `TypeError`, not panic.
+
+    #[tokio::test]
+    async fn test_array_decoder_too_short() {
+	let mut buf = vec!['A' as u8, 0x00, 0x00, 0x00, 0x02];
+
+	buf.extend_from_slice(&Type::Int(1).to_redis_args()[0]);
+
+	let data = Value::Data(buf);
+
+	assert!(from_redis_value::<Type>(&data).is_err());
+    }
+
+    // `index` returns the element at a valid position...
+
+    #[tokio::test]
+    async fn test_index_in_range() {
+	let value = Type::Array(vec![Type::Int(1), Type::Int(2)]);
+
+	match value.index(1) {
+	    Ok(v) => assert_eq!(&Type::Int(2), v),
+	    Err(e) => panic!("expected element at index 1, got error {:?}", e)
+	}
+    }
+
+    // ...and an explicit error, rather than a panic, when the index is
+    // out of range.
+
+    #[tokio::test]
+    async fn test_index_out_of_range() {
+	let value = Type::Array(vec![Type::Int(1), Type::Int(2)]);
+
+	assert!(value.index(2).is_err());
+    }
 }
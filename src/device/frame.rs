@@ -0,0 +1,89 @@
+// A buffered, self-describing framing layer for device protocols that
+// send a timestamp/value pair per frame. Each frame is a small, fixed
+// header -- a magic byte followed by a big-endian `u32` payload length
+// -- followed by the payload itself. Validating the header before
+// trusting the payload means a partial or misaligned frame no longer
+// silently corrupts the timestamp/value pair: a bad magic byte or a
+// length that doesn't match what this reader expects is skipped, one
+// byte at a time, until a valid frame boundary turns up again, instead
+// of killing the connection. `FrameReader` wraps the buffered,
+// resync-capable decode so future device drivers can share it instead
+// of each re-implementing raw `AsyncReadExt` calls.
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+
+use crate::error::Result;
+
+const MAGIC: u8 = 0xda;
+const PAYLOAD_LEN: u32 = 12; // an 8-byte timestamp plus a 4-byte value
+
+pub struct FrameReader<R> {
+    inner: BufReader<R>,
+    buf: Vec<u8>,
+
+    // Bytes that were read while looking for a length field but turned
+    // out not to start one; they're re-scanned, one at a time, before
+    // any new bytes are pulled off the socket.
+
+    pending: VecDeque<u8>
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(inner: R) -> FrameReader<R> {
+	FrameReader { inner: BufReader::new(inner),
+		     buf: vec![0u8; PAYLOAD_LEN as usize],
+		     pending: VecDeque::new() }
+    }
+
+    // Returns the next byte in the stream, preferring anything still
+    // sitting in `pending` over reading a fresh one off the socket.
+
+    async fn read_byte(&mut self) -> Result<u8> {
+	if let Some(b) = self.pending.pop_front() {
+	    return Ok(b)
+	}
+	Ok(self.inner.read_u8().await?)
+    }
+
+    // Reads the next valid `(timestamp, value)` frame, resync-ing past
+    // a bad magic byte or length field, one byte at a time, rather than
+    // returning an error for a frame that's merely out of alignment.
+
+    pub async fn next_frame(&mut self) -> Result<(u64, bool)> {
+	loop {
+	    if self.read_byte().await? != MAGIC {
+		continue;
+	    }
+
+	    let mut len_buf = [0u8; 4];
+
+	    for b in len_buf.iter_mut() {
+		*b = self.read_byte().await?;
+	    }
+
+	    if u32::from_be_bytes(len_buf) != PAYLOAD_LEN {
+
+		// The byte we matched as MAGIC wasn't the start of a
+		// real frame after all. Put the bytes we just read for
+		// the length field back in front of the stream and
+		// resume scanning for MAGIC from the first of them,
+		// instead of discarding all four and potentially
+		// skipping over the real frame boundary.
+
+		for b in len_buf.iter().rev() {
+		    self.pending.push_front(*b);
+		}
+		continue;
+	    }
+
+	    self.inner.read_exact(&mut self.buf).await?;
+
+	    let stamp = u64::from_be_bytes(self.buf[..8].try_into().unwrap());
+	    let value = u32::from_be_bytes(self.buf[8..12].try_into().unwrap());
+
+	    return Ok((stamp, value != 0))
+	}
+    }
+}
@@ -0,0 +1,154 @@
+// `device` is the home for anything the reactor can poll for data. A
+// `Device` owns its own connection and protocol; the reactor only
+// needs to know how to ask it for the next reading and which prefix
+// its history streams live under in Redis.
+
+use async_trait::async_trait;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use tracing::error;
+
+use crate::error::Result;
+
+pub mod backoff;
+pub mod data;
+pub mod frame;
+pub mod sump;
+
+pub use data::Type;
+
+// A single measurement produced by a device. `stamp` is `Some(t)` when
+// the device itself timestamps the event (e.g. the sump pump process
+// tags each on/off transition); it's `None` when there's no device
+// clock to defer to, in which case Redis assigns the stream ID. Each
+// entry becomes its own history stream, named
+// "<redis_prefix>:<entry-name>.hist", with its fields written
+// together in a single `XADD`. All of a reading's entries are recorded
+// in one atomic pipeline, so e.g. a "state" entry and the "duty"/
+// "in-flow" entries derived from it never land with one present and
+// the others missing.
+
+pub struct Reading {
+    pub stamp: Option<u64>,
+    pub entries: Vec<(&'static str, Vec<(&'static str, Type)>)>
+}
+
+impl Reading {
+
+    // Builds a reading tagged with the device's own timestamp.
+
+    pub fn at(stamp: u64) -> Reading {
+	Reading { stamp: Some(stamp), entries: Vec::new() }
+    }
+
+    // Builds a reading with no device timestamp; Redis will assign the
+    // stream ID when it's recorded.
+
+    pub fn now() -> Reading {
+	Reading { stamp: None, entries: Vec::new() }
+    }
+
+    // Adds a single-field entry and returns the reading, so readings
+    // can be built up with a chain of calls. The field is named
+    // "value", matching how a single-valued stream has always been
+    // written here.
+
+    pub fn with(self, name: &'static str, value: Type) -> Reading {
+	self.with_fields(name, vec![("value", value)])
+    }
+
+    // Adds an entry made up of several fields that belong together in
+    // one `XADD` -- e.g. a "service" entry that carries its state
+    // alongside the reconnect attempt count and backoff interval that
+    // produced it.
+
+    pub fn with_fields(mut self, name: &'static str,
+		       fields: Vec<(&'static str, Type)>) -> Reading {
+	self.entries.push((name, fields));
+	self
+    }
+}
+
+// Anything that can be polled for a stream of `Reading`s. Implementors
+// are free to hold a socket, a serial port, their own state machine --
+// whatever the device's protocol needs -- as long as each call to
+// `next_reading` eventually resolves to the next thing worth recording.
+
+#[async_trait]
+pub trait Device: Send {
+    async fn next_reading(&mut self) -> Result<Reading>;
+
+    fn redis_prefix(&self) -> &str;
+}
+
+// Drives a set of devices concurrently from one task. Each device is
+// polled for its next reading with `FuturesUnordered` so that a slow
+// or idle device never holds up the others; as soon as a reading comes
+// in, it's written to the device's Redis stream(s) and the device is
+// immediately queued for its next reading. All devices share the one
+// Redis connection passed in.
+
+pub async fn run(mut con: redis::aio::Connection,
+		 devices: Vec<Box<dyn Device>>) -> Result<()> {
+    let mut pending = FuturesUnordered::new();
+
+    for dev in devices {
+	pending.push(poll(dev));
+    }
+
+    while let Some((dev, result)) = pending.next().await {
+	match result {
+	    Ok(reading) => {
+		if let Err(e) = record(&mut con, dev.redis_prefix(), &reading).await {
+		    error!("couldn't record reading from {} -- {:?}",
+			   dev.redis_prefix(), e);
+		}
+	    },
+	    Err(e) =>
+		error!("{} device error -- {:?}", dev.redis_prefix(), e)
+	}
+	pending.push(poll(dev));
+    }
+    Ok(())
+}
+
+// Polls a single device for its next reading and hands ownership of it
+// back along with the result, so it can be re-queued in the
+// `FuturesUnordered` set that `run` maintains.
+
+async fn poll(mut dev: Box<dyn Device>) -> (Box<dyn Device>, Result<Reading>) {
+    let result = dev.next_reading().await;
+
+    (dev, result)
+}
+
+// Writes every entry of a `Reading` to its own Redis stream, named
+// "<prefix>:<entry-name>.hist", as one atomic pipeline -- so a reading
+// with more than one entry (e.g. "state" plus the "duty"/"in-flow" it
+// triggers) is never left partially recorded by a failure part-way
+// through.
+
+async fn record(con: &mut redis::aio::Connection, prefix: &str, reading: &Reading)
+		-> Result<()> {
+    if reading.entries.is_empty() {
+	return Ok(())
+    }
+
+    let id = reading.stamp.map(|v| v.to_string()).unwrap_or_else(|| "*".to_string());
+    let mut pipe = redis::pipe();
+
+    pipe.atomic();
+
+    for (name, fields) in &reading.entries {
+	let key = format!("{}:{}.hist", prefix, name);
+	let cmd = pipe.cmd("XADD").arg(&key).arg(&id);
+
+	for (field, value) in fields {
+	    cmd.arg(*field).arg(value);
+	}
+	cmd.ignore();
+    }
+
+    let _: () = pipe.query_async(con).await?;
+    Ok(())
+}
@@ -0,0 +1,241 @@
+// The sump pump device: connects to the separate sump-pump monitor
+// process over TCP and turns its raw on/off frames into `Reading`s --
+// the state transition itself, plus the duty cycle and in-flow rate
+// once a full off/on cycle has been observed.
+
+use std::net::SocketAddrV4;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::delay_for;
+use tokio::sync::mpsc;
+use tracing::{info, error};
+use palette::{Srgb, Yxy};
+use palette::named;
+use async_trait::async_trait;
+
+use crate::hue;
+use crate::error::Result;
+use super::{Device, Reading};
+use super::backoff::Backoff;
+use super::data::Type;
+use super::frame::FrameReader;
+
+// The sump pump monitor uses a state machine to decide when to
+// calculate the duty cycle and in-flow.
+
+#[derive(Debug)]
+enum State {
+    Unknown,
+    Off { off_time: u64 },
+    On { off_time: u64, on_time: u64 }
+}
+
+// This interface allows a State value to update itself when an event
+// occurs.
+
+impl State {
+
+    // This method is called when an off event occurs. The timestamp
+    // of the off event needs to be provided. If the state machine has
+    // enough information of the previous pump cycle, it will return
+    // the duty cycle and in-flow rate. If the state machine is still
+    // sync-ing with the state, the state will get updated, but `None`
+    // will be returned.
+
+    pub fn to_off(&mut self, stamp: u64) -> Option<(f64, f64)> {
+	match *self {
+	    State::Unknown => {
+		info!("sync-ed with OFF state");
+		*self = State::Off { off_time: stamp };
+		None
+	    },
+
+	    State::Off { off_time: _ } => {
+		info!("ignoring duplicate OFF event");
+		None
+	    },
+
+	    State::On { off_time, on_time } => {
+		let on_time = ((stamp - on_time) as f64) / 1000.0;
+		let off_time = ((stamp - off_time) as f64) / 1000.0;
+		let duty = (on_time * 100.0 / off_time).round();
+		let in_flow = (2680.0 * duty / 60.0).round() / 100.0;
+
+		*self = State::Off { off_time: stamp };
+		Some((duty, in_flow))
+	    }
+	}
+    }
+
+    // This method is called when updating the state with an on
+    // event. The timestamp of the on event needs to be provided. If
+    // the on event actually caused a state change, `true` is
+    // returned.
+
+    pub fn to_on(&mut self, stamp: u64) -> bool {
+	match *self {
+	    State::Unknown => false,
+
+	    State::Off { off_time } => {
+		*self = State::On { off_time, on_time: stamp };
+		true
+	    },
+
+	    State::On { .. } => {
+		info!("ignoring duplicate ON event");
+		false
+	    }
+	}
+    }
+}
+
+async fn lamp_alert(tx: &mut mpsc::Sender<hue::Program>) {
+    let b : Yxy = Srgb::<f32>::from_format(named::BLUE).into_linear().into();
+    let r : Yxy = Srgb::<f32>::from_format(named::RED).into_linear().into();
+    let prog =
+	vec![hue::HueCommands::On { light: 5, bri: 255, color: Some(b) },
+	     hue::HueCommands::On { light: 8, bri: 255, color: Some(b) },
+	     hue::HueCommands::Pause { len: Duration::from_millis(500) },
+	     hue::HueCommands::On { light: 5, bri: 255, color: Some(r) },
+	     hue::HueCommands::On { light: 8, bri: 255, color: Some(r) },
+	     hue::HueCommands::Pause { len: Duration::from_millis(5_000) },
+	     hue::HueCommands::Off { light: 5 },
+	     hue::HueCommands::Off { light: 8 }];
+
+    tx.send(prog).await;
+}
+
+async fn lamp_off(tx: &mut mpsc::Sender<hue::Program>, duty: f64) {
+    let prog = if duty < 10.0 {
+	vec![hue::HueCommands::Off { light: 5 },
+	     hue::HueCommands::Off { light: 8 }]
+    } else {
+	let cc = if duty < 30.0 { named::YELLOW } else { named::RED };
+	let c : Yxy = Srgb::<f32>::from_format(cc).into_linear().into();
+
+	vec![hue::HueCommands::On { light: 5, bri: 255, color: Some(c) },
+	     hue::HueCommands::On { light: 8, bri: 255, color: Some(c) },
+	     hue::HueCommands::Pause { len: Duration::from_millis(5_000) },
+	     hue::HueCommands::Off { light: 5 },
+	     hue::HueCommands::Off { light: 8 }]
+    };
+
+    tx.send(prog).await;
+}
+
+// Connects to the sump pump monitor process, watches its on/off
+// frames, and reports them -- along with the duty cycle and in-flow
+// rate it derives from them -- as `Reading`s under the "sump" prefix.
+
+pub struct SumpPump {
+    addr: SocketAddrV4,
+    tx: mpsc::Sender<hue::Program>,
+    state: State,
+    stream: Option<FrameReader<TcpStream>>,
+    c_on: Yxy,
+    backoff: Backoff
+}
+
+impl SumpPump {
+    pub fn new(addr: SocketAddrV4, tx: mpsc::Sender<hue::Program>) -> SumpPump {
+	let c_on : Yxy = Srgb::<f32>::from_format(named::BLUE).into_linear().into();
+	let backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(300));
+
+	SumpPump { addr, tx, state: State::Unknown, stream: None, c_on, backoff }
+    }
+}
+
+#[async_trait]
+impl Device for SumpPump {
+
+    // Returns the next reading worth recording: a connect/disconnect
+    // of the underlying socket is itself reported as a "service"
+    // reading, and a frame from the monitor process is reported as a
+    // "state" reading (with "duty"/"in-flow" added once a full cycle
+    // has been observed).
+
+    async fn next_reading(&mut self) -> Result<Reading> {
+	loop {
+	    match self.stream.take() {
+		None => match TcpStream::connect(self.addr).await {
+		    Ok(s) => {
+			info!("connected to sump pump process");
+			self.stream = Some(FrameReader::new(s));
+			self.state = State::Unknown;
+			return Ok(Reading::now().with("service", Type::Str("up".to_string())))
+		    },
+		    Err(e) => {
+			error!("couldn't connect to pump process -- {:?}", e);
+			lamp_alert(&mut self.tx).await;
+
+			let delay = self.backoff.next_delay();
+			let reading = Reading::now()
+			    .with_fields("service",
+					 vec![("value", Type::Str("down".to_string())),
+					      ("attempts", Type::Int(self.backoff.attempts() as i64)),
+					      ("backoff-ms", Type::Int(delay.as_millis() as i64))]);
+
+			delay_for(delay).await;
+			return Ok(reading)
+		    }
+		},
+
+		Some(mut reader) => {
+		    let result = reader.next_frame().await;
+
+		    match result {
+			Ok((stamp, true)) => {
+			    self.stream = Some(reader);
+			    self.backoff.reset();
+			    if self.state.to_on(stamp) {
+				let sump_on =
+				    vec![hue::HueCommands::On { light: 5, bri: 255,
+								color: Some(self.c_on) },
+					 hue::HueCommands::On { light: 8, bri: 255,
+								color: Some(self.c_on) }];
+				self.tx.send(sump_on).await;
+			    }
+			    return Ok(Reading::at(stamp)
+				      .with("state", Type::Str("on".to_string())))
+			},
+
+			Ok((stamp, false)) => {
+			    self.stream = Some(reader);
+			    self.backoff.reset();
+
+			    let mut reading = Reading::at(stamp)
+				.with("state", Type::Str("off".to_string()));
+
+			    if let Some((duty, in_flow)) = self.state.to_off(stamp) {
+				info!("duty: {}%, in flow: {} gpm", duty, in_flow);
+				lamp_off(&mut self.tx, duty).await;
+				reading = reading.with("duty", Type::Flt(duty))
+				    .with("in-flow", Type::Flt(in_flow));
+			    }
+			    return Ok(reading)
+			},
+
+			Err(e) => {
+			    error!("couldn't read sump state -- {:?}", e);
+			    lamp_alert(&mut self.tx).await;
+
+			    let delay = self.backoff.next_delay();
+			    let reading = Reading::now()
+				.with_fields("service",
+					     vec![("value", Type::Str("crash".to_string())),
+						  ("attempts", Type::Int(self.backoff.attempts() as i64)),
+						  ("backoff-ms", Type::Int(delay.as_millis() as i64))]);
+
+			    delay_for(delay).await;
+			    return Ok(reading)
+			}
+		    }
+		}
+	    }
+	}
+    }
+
+    fn redis_prefix(&self) -> &str {
+	"sump"
+    }
+}
@@ -0,0 +1,47 @@
+// A single error type for the crate. Every fallible function used to
+// return `redis::RedisResult`, which forced socket errors to be
+// shoehorned into `RedisError`. Keeping them distinct here means the
+// difference between "pump socket died" and "Redis is unreachable" is
+// visible at the call site, and `main` can print the actual chain of
+// causes instead of a generic Redis error.
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    Redis(redis::RedisError),
+    Io(io::Error)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    Error::Redis(e) => write!(f, "redis error -- {}", e),
+	    Error::Io(e) => write!(f, "I/O error -- {}", e)
+	}
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+	match self {
+	    Error::Redis(e) => Some(e),
+	    Error::Io(e) => Some(e)
+	}
+    }
+}
+
+impl From<redis::RedisError> for Error {
+    fn from(e: redis::RedisError) -> Error {
+	Error::Redis(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+	Error::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;